@@ -7,7 +7,7 @@ fn forward_once<T: Display>(session: Recv<T, Snd<T, Return>>) {
     recv!(session, x);
     println!("Forwarding {x} in thread {:?}", thread::current().id());
     send!(session, x);
-    drop(session);
+    let _ = session;
 }
 
 
@@ -1,18 +1,64 @@
+use std::io::{Read, Write};
 use std::marker::PhantomData;
+use std::sync::Mutex;
 use kanal::{Receiver, Sender};
 
+// A transport is anything a session can send payloads over and receive payloads from. Abstracting
+// this out means a session is no longer tied to in-process kanal channels - the same `Snd`/`Recv`
+// structure can run over a socket, a pipe, or anything else that implements this trait.
+pub trait Transport<T> {
+    type Error: std::fmt::Debug;
+    fn send(&self, payload: T) -> Result<(), Self::Error>;
+    fn recv(&self) -> Result<T, Self::Error>;
+}
+
+// In-process kanal channels are simplex, so each half only implements the direction it supports;
+// the unused direction is unreachable because `Snd` only ever sends and `Recv` only ever receives.
+impl<T> Transport<T> for Sender<T> {
+    type Error = kanal::SendError;
+    fn send(&self, payload: T) -> Result<(), Self::Error> {
+        Sender::send(self, payload)
+    }
+    fn recv(&self) -> Result<T, Self::Error> {
+        unreachable!("a kanal Sender transport is send-only")
+    }
+}
+
+impl<T> Transport<T> for Receiver<T> {
+    type Error = kanal::ReceiveError;
+    fn send(&self, _payload: T) -> Result<(), Self::Error> {
+        unreachable!("a kanal Receiver transport is receive-only")
+    }
+    fn recv(&self) -> Result<T, Self::Error> {
+        Receiver::recv(self)
+    }
+}
+
 // Session Types
 // (Send is a part of the stdlib prelude)
-pub struct Snd<T, Cont>(Sender<T>, Cont, PanicOnDrop);
-pub struct Recv<T, Cont>(Receiver<T>, Cont, PanicOnDrop);
+pub struct Snd<T, Cont, Tr = Sender<T>>(Tr, Cont, PanicOnDrop, PhantomData<T>);
+pub struct Recv<T, Cont, Tr = Receiver<T>>(Tr, Cont, PanicOnDrop, PhantomData<T>);
 pub struct End;
 pub struct Return<'a>(PhantomData<&'a ()>);
 
+// Internal choice: the active side picks a branch and the peer must follow. The discriminant is a
+// single `bool` sent over its own channel (`false` => left, `true` => right); the untaken branch is
+// forgotten rather than dropped so its `PanicOnDrop` guards do not fire.
+pub struct Choose<L, R>(Sender<bool>, L, R, PanicOnDrop);
+// External choice: read the discriminant and continue with the matching branch.
+pub struct Offer<L, R>(Receiver<bool>, L, R, PanicOnDrop);
+
+// The branch an `Offer` resolved to once its discriminant has been received.
+pub enum Branch<L, R> {
+    Left(L),
+    Right(R),
+}
+
 
 // Actual usage API
-impl<T, Cont> Snd<T, Cont> {
-    pub fn new(sender: Sender<T>, cont: Cont) -> Self {
-        Self(sender, cont, PanicOnDrop)
+impl<T, Cont, Tr: Transport<T>> Snd<T, Cont, Tr> {
+    pub fn new(transport: Tr, cont: Cont) -> Self {
+        Self(transport, cont, PanicOnDrop::new(), PhantomData)
     }
     pub fn send(self, payload: T) -> Cont {
         self.0.send(payload).unwrap();
@@ -22,9 +68,9 @@ impl<T, Cont> Snd<T, Cont> {
 }
 
 
-impl<T, Cont> Recv<T, Cont> {
-    pub fn new(receiver: Receiver<T>, cont: Cont) -> Self {
-        Self(receiver, cont, PanicOnDrop)
+impl<T, Cont, Tr: Transport<T>> Recv<T, Cont, Tr> {
+    pub fn new(transport: Tr, cont: Cont) -> Self {
+        Self(transport, cont, PanicOnDrop::new(), PhantomData)
     }
     pub fn recv(self) -> (T, Cont) {
         let result = self.0.recv().unwrap();
@@ -34,23 +80,272 @@ impl<T, Cont> Recv<T, Cont> {
 }
 
 
+// A serde-backed transport over any `Read + Write` (for example a `TcpStream`), letting a session
+// span processes or machines. Each payload is framed exactly as the proc-macro bridges frame their
+// RPC bodies: a little-endian `u64` length followed by that many bytes of the encoded body. The
+// stream is wrapped in a `Mutex` so the shared `&self` API can take exclusive access for the
+// duration of a frame.
+pub struct SerdeTransport<S> {
+    inner: Mutex<S>,
+}
+
+#[derive(Debug)]
+pub enum SerdeError {
+    Io(std::io::Error),
+    Encode(bincode::Error),
+}
+
+impl<S> SerdeTransport<S> {
+    pub fn new(stream: S) -> Self {
+        Self { inner: Mutex::new(stream) }
+    }
+}
+
+impl<S, T> Transport<T> for SerdeTransport<S>
+where
+    S: Read + Write,
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    type Error = SerdeError;
+
+    fn send(&self, payload: T) -> Result<(), Self::Error> {
+        let body = bincode::serialize(&payload).map_err(SerdeError::Encode)?;
+        let mut stream = self.inner.lock().unwrap();
+        stream
+            .write_all(&(body.len() as u64).to_le_bytes())
+            .map_err(SerdeError::Io)?;
+        stream.write_all(&body).map_err(SerdeError::Io)?;
+        stream.flush().map_err(SerdeError::Io)?;
+        Ok(())
+    }
+
+    fn recv(&self) -> Result<T, Self::Error> {
+        let mut stream = self.inner.lock().unwrap();
+        let mut len = [0u8; 8];
+        stream.read_exact(&mut len).map_err(SerdeError::Io)?;
+        let len = u64::from_le_bytes(len) as usize;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).map_err(SerdeError::Io)?;
+        bincode::deserialize(&body).map_err(SerdeError::Encode)
+    }
+}
+
+
+// An opt-in transport wrapper that pins a session to the thread it was created on. kanal endpoints
+// are `Send`, so a half-completed session can otherwise be moved to another thread mid-protocol;
+// when the session guards thread-local resources that is a bug. This remembers the originating
+// `ThreadId` at construction and asserts it on every `send`/`recv`, composing with `PanicOnDrop`:
+// one guard catches an unused session, the other catches a migrated one.
+pub struct ThreadBound<Tr> {
+    inner: Tr,
+    owner: std::thread::ThreadId,
+}
+
+impl<Tr> ThreadBound<Tr> {
+    pub fn new(inner: Tr) -> Self {
+        Self { inner, owner: std::thread::current().id() }
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(
+            std::thread::current().id(),
+            self.owner,
+            "session used from a different thread than it was created on"
+        );
+    }
+}
+
+impl<Tr, T> Transport<T> for ThreadBound<Tr>
+where
+    Tr: Transport<T>,
+{
+    type Error = Tr::Error;
+
+    fn send(&self, payload: T) -> Result<(), Self::Error> {
+        self.assert_owner();
+        self.inner.send(payload)
+    }
+
+    fn recv(&self) -> Result<T, Self::Error> {
+        self.assert_owner();
+        self.inner.recv()
+    }
+}
+
+// Thread-bound session endpoints. `LocalSnd::new(ThreadBound::new(tx), cont)` builds a sender that
+// panics if it is ever used off its creating thread.
+pub type LocalSnd<T, Cont> = Snd<T, Cont, ThreadBound<Sender<T>>>;
+pub type LocalRecv<T, Cont> = Recv<T, Cont, ThreadBound<Receiver<T>>>;
+
+
+impl<L, R> Choose<L, R> {
+    pub fn new(sender: Sender<bool>, left: L, right: R) -> Self {
+        Self(sender, left, right, PanicOnDrop::new())
+    }
+
+    pub fn choose_left(self) -> L {
+        let Self(sender, left, right, guard) = self;
+        sender.send(false).unwrap();
+        guard.disarm();
+        // The peer will never run the right branch, so leak it instead of letting its guards panic.
+        std::mem::forget(right);
+        left
+    }
+
+    pub fn choose_right(self) -> R {
+        let Self(sender, left, right, guard) = self;
+        sender.send(true).unwrap();
+        guard.disarm();
+        std::mem::forget(left);
+        right
+    }
+}
+
+
+impl<L, R> Offer<L, R> {
+    pub fn new(receiver: Receiver<bool>, left: L, right: R) -> Self {
+        Self(receiver, left, right, PanicOnDrop::new())
+    }
+
+    pub fn offer(self) -> Branch<L, R> {
+        let Self(receiver, left, right, guard) = self;
+        let tag = receiver.recv().unwrap();
+        guard.disarm();
+        if tag {
+            std::mem::forget(left);
+            Branch::Right(right)
+        } else {
+            std::mem::forget(right);
+            Branch::Left(left)
+        }
+    }
+}
+
+
+// Duality ties the two endpoints of a session together: every `Snd` on one side is a `Recv` on the
+// other, every `Choose` an `Offer`, and so on recursively. Making this an associated type lets the
+// compiler reject a pair of endpoints that do not agree on the protocol.
+pub trait Dual {
+    type Dual;
+
+    // Allocate the channels this protocol needs and return both dual endpoints already wired
+    // together over the shared handles.
+    fn pair() -> (Self, Self::Dual)
+    where
+        Self: Sized;
+}
+
+
+impl Dual for End {
+    type Dual = End;
+
+    fn pair() -> (Self, Self::Dual) {
+        (End, End)
+    }
+}
+
+
+// Duality is an in-process notion: `pair()` allocates kanal channels, so the dual endpoints use the
+// kanal transports.
+impl<T, C: Dual> Dual for Snd<T, C, Sender<T>> {
+    type Dual = Recv<T, C::Dual, Receiver<T>>;
+
+    fn pair() -> (Self, Self::Dual) {
+        let (tx, rx) = kanal::bounded::<T>(0);
+        let (cont, cont_dual) = C::pair();
+        (Snd::new(tx, cont), Recv::new(rx, cont_dual))
+    }
+}
+
+
+impl<T, C: Dual> Dual for Recv<T, C, Receiver<T>> {
+    type Dual = Snd<T, C::Dual, Sender<T>>;
+
+    fn pair() -> (Self, Self::Dual) {
+        let (tx, rx) = kanal::bounded::<T>(0);
+        let (cont, cont_dual) = C::pair();
+        (Recv::new(rx, cont), Snd::new(tx, cont_dual))
+    }
+}
+
+
+impl<L: Dual, R: Dual> Dual for Choose<L, R> {
+    type Dual = Offer<L::Dual, R::Dual>;
+
+    fn pair() -> (Self, Self::Dual) {
+        let (tx, rx) = kanal::bounded::<bool>(0);
+        let (l, l_dual) = L::pair();
+        let (r, r_dual) = R::pair();
+        (Choose::new(tx, l, r), Offer::new(rx, l_dual, r_dual))
+    }
+}
+
+
+impl<L: Dual, R: Dual> Dual for Offer<L, R> {
+    type Dual = Choose<L::Dual, R::Dual>;
+
+    fn pair() -> (Self, Self::Dual) {
+        let (tx, rx) = kanal::bounded::<bool>(0);
+        let (l, l_dual) = L::pair();
+        let (r, r_dual) = R::pair();
+        (Offer::new(rx, l, r), Choose::new(tx, l_dual, r_dual))
+    }
+}
+
+
+// Construct both endpoints of a session at once, allocating the channels a single time and handing
+// the matching halves to the two dual type structures.
+pub fn session_pair<S: Dual>() -> (S, S::Dual) {
+    S::pair()
+}
+
+
 // We have only affine types, rather than linear types. This means that a user could borrow some
 // prefix, then drop it without using it. This would allow them to violate the protocol.
 // Panicking in the drop implementation means we can detect this at runtime (although a compile time
 // check would be preferred if it were possible)
-struct PanicOnDrop;
+// By default this is a zero-sized guard so the hot path stays allocation-free. Under the
+// `backtrace` feature it additionally remembers where the session was created, which makes the
+// drop-time panic point at the offending session in a program running many of them at once.
+struct PanicOnDrop {
+    #[cfg(feature = "backtrace")]
+    created_at: Option<std::backtrace::Backtrace>,
+}
 
 impl PanicOnDrop {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "backtrace")]
+            created_at: capture_backtrace(),
+        }
+    }
+
     fn disarm(self) {
         std::mem::forget(self);
     }
 }
 
+// `Backtrace::capture` already honours `RUST_BACKTRACE`/`RUST_LIB_BACKTRACE` and returns a disabled
+// backtrace (doing no unwinding) when neither is set, so we only keep the result when it actually
+// captured something.
+#[cfg(feature = "backtrace")]
+fn capture_backtrace() -> Option<std::backtrace::Backtrace> {
+    let backtrace = std::backtrace::Backtrace::capture();
+    match backtrace.status() {
+        std::backtrace::BacktraceStatus::Captured => Some(backtrace),
+        _ => None,
+    }
+}
+
 impl Drop for PanicOnDrop {
     fn drop(&mut self) {
         // Do not panic if we are already panicking, this would trigger an immediate abort and could
         // obscure the original error
         if !std::thread::panicking() {
+            #[cfg(feature = "backtrace")]
+            if let Some(created_at) = &self.created_at {
+                panic!("Dropped a session before it was used. Session created at:\n{created_at}")
+            }
             panic!("Dropped a session before it was used")
         }
     }
@@ -64,6 +359,14 @@ pub trait Split<Into> {
     // Arguably, this shouldn't be unsafe, because it shouldn't allow for memory/thread safety
     // violations, however we are attempting to extend the guarantees using session types, and this
     // does allow violating our new guarantees.
+    ///
+    /// # Safety
+    ///
+    /// This hands out a borrowed prefix alongside the remainder, both over the same transport. The
+    /// caller must drive the prefix to completion (so the peer sees exactly the prefix's
+    /// communications) before using the remainder; otherwise the two halves interleave on the wire
+    /// and the protocol desyncs. The `split!` macro upholds this by tying the prefix's lifetime to
+    /// the remainder.
     unsafe fn split(self) -> (Into, Self::Remainder);
 }
 
@@ -80,17 +383,17 @@ impl Split<Return<'static>> for End {
 
 // Send<T, Cont> can be split into Send<T, P>, Remainder for any P, Remainder than Cont can
 // be split into
-impl<T, P, Cont> Split<Snd<T, P>> for Snd<T, Cont> where Cont: Split<P> {
+impl<T, P, Cont, Tr> Split<Snd<T, P, Tr>> for Snd<T, Cont, Tr> where Cont: Split<P> {
     type Remainder = Cont::Remainder;
 
-    unsafe fn split(self) -> (Snd<T, P>, Self::Remainder) {
+    unsafe fn split(self) -> (Snd<T, P, Tr>, Self::Remainder) {
         let (p, remainder) = unsafe{self.1.split()};
-        (Snd(self.0, p, self.2), remainder)
+        (Snd(self.0, p, self.2, PhantomData), remainder)
     }
 }
 
 // Alternatively we could just take an "empty" (only return) session and leave the rest behind
-impl<T, Cont> Split<Return<'static>> for Snd<T, Cont> {
+impl<T, Cont, Tr> Split<Return<'static>> for Snd<T, Cont, Tr> {
     type Remainder = Self;
 
     unsafe fn split(self) -> (Return<'static>, Self::Remainder) {
@@ -101,18 +404,133 @@ impl<T, Cont> Split<Return<'static>> for Snd<T, Cont> {
 
 // Recv<T, Cont> can be split into Send<T, P>, Remainder for any P, Remainder than Cont can
 // be split into
-impl<T, P, Cont> Split<Recv<T, P>> for Recv<T, Cont> where Cont: Split<P> {
+impl<T, P, Cont, Tr> Split<Recv<T, P, Tr>> for Recv<T, Cont, Tr> where Cont: Split<P> {
     type Remainder = Cont::Remainder;
 
-    unsafe fn split(self) -> (Recv<T, P>, Self::Remainder) {
+    unsafe fn split(self) -> (Recv<T, P, Tr>, Self::Remainder) {
         let (p, remainder) = unsafe{self.1.split()};
-        (Recv(self.0, p, self.2), remainder)
+        (Recv(self.0, p, self.2, PhantomData), remainder)
     }
 }
 
 
 // Alternatively we could just take an "empty" (only return) session and leave the rest behind
-impl<T, Cont> Split<Return<'static>> for Recv<T, Cont> {
+impl<T, Cont, Tr> Split<Return<'static>> for Recv<T, Cont, Tr> {
+    type Remainder = Self;
+
+    unsafe fn split(self) -> (Return<'static>, Self::Remainder) {
+        (Return(PhantomData), self)
+    }
+}
+
+
+// A choice is a single communication: one discriminant travels over the wire for one logical
+// decision. Borrowing "through" a choice would clone that discriminant into both the prefix and the
+// remainder, putting two discriminants on the wire for one choice and desyncing against a dual
+// `Offer`'s single bounded `recv`. So `Choose`/`Offer` can only be borrowed *up to* the choice
+// point: the only `Split` they admit takes an empty (`Return`) prefix and leaves the whole choice
+// behind as the remainder.
+impl<L, R> Split<Return<'static>> for Choose<L, R> {
+    type Remainder = Self;
+
+    unsafe fn split(self) -> (Return<'static>, Self::Remainder) {
+        (Return(PhantomData), self)
+    }
+}
+
+
+// As with `Choose`, an `Offer` can only be borrowed up to the choice point: the discriminant is a
+// single bounded `recv`, so the remainder keeps the whole choice.
+impl<L, R> Split<Return<'static>> for Offer<L, R> {
+    type Remainder = Self;
+
+    unsafe fn split(self) -> (Return<'static>, Self::Remainder) {
+        (Return(PhantomData), self)
+    }
+}
+
+
+// Recursive session types
+//
+// A streaming protocol such as "loop { recv T; choose continue | stop }" has a type that would be
+// infinite if written out, so we tie the knot with an equi-recursive pair. `F` is a type-level
+// function from the loop variable to a body, encoded as a trait with a generic associated type so
+// the same body shape can be instantiated with whatever the variable stands for. `Rec<F>` is the
+// loop itself and `Var` marks the point inside the body where the loop begins again; one unfolding
+// of `Rec<F>` has type `F::Body<Var<Rec<F>>>`.
+pub trait SessionFn {
+    type Body<V>;
+}
+
+// The recursion variable: inside a body it stands for "the whole loop again". It carries a thunk
+// that rebuilds the `Rec` it stands for on demand, when `recurse` is called; unfolding that `Rec`
+// again is left to its own `enter`. The thunk is `Send` so a recursive session stays movable
+// across threads like every other session type. It carries the same linearity guard so an unused unfolding is still caught at
+// runtime.
+pub struct Var<R>(Box<dyn FnOnce() -> R + Send>, PanicOnDrop);
+
+// The step that produces one unfolding of a loop body from the recursion variable. Shared behind an
+// `Arc` so each unfolding can hand a clone to the `Var` that rebuilds the next one, and `Send +
+// Sync` so the loop can be moved to a worker thread.
+type RecStep<F> =
+    std::sync::Arc<dyn Fn(Var<Rec<F>>) -> <F as SessionFn>::Body<Var<Rec<F>>> + Send + Sync>;
+
+// An equi-recursive session. Rather than storing a pre-built (and therefore infinite) body, it
+// stores the step that produces one unfolding of the loop body from the recursion variable.
+// Unfolding it once substitutes `Var` with a thunk that folds the whole `Rec` back up again.
+pub struct Rec<F: SessionFn + 'static>(RecStep<F>, PanicOnDrop);
+
+
+impl<R> Var<R> {
+    pub fn new(rebuild: impl FnOnce() -> R + Send + 'static) -> Self {
+        Self(Box::new(rebuild), PanicOnDrop::new())
+    }
+
+    // Run another iteration: rebuild and recover the loop that this variable stands for.
+    pub fn recurse(self) -> R {
+        self.1.disarm();
+        (self.0)()
+    }
+}
+
+
+impl<F: SessionFn + 'static> Rec<F> {
+    pub fn new(step: impl Fn(Var<Rec<F>>) -> F::Body<Var<Rec<F>>> + Send + Sync + 'static) -> Self {
+        Self(std::sync::Arc::new(step), PanicOnDrop::new())
+    }
+
+    // Unfold one iteration, handing back the body with `Var` marking the loop point. The `Var` holds
+    // a thunk over this same step, so reaching it and calling `recurse` rebuilds the `Rec` and folds
+    // the loop back up for the next iteration.
+    pub fn enter(self) -> F::Body<Var<Rec<F>>> {
+        self.1.disarm();
+        let step = std::sync::Arc::clone(&self.0);
+        let var = Var::new(move || Rec(step, PanicOnDrop::new()));
+        (self.0)(var)
+    }
+}
+
+
+// Borrowing a bounded number of unfoldings: splitting a `Rec` unfolds one iteration and splits its
+// body, so a worker can borrow the prefix up to the next `Var` (one iteration) and hand the loop
+// back, exactly like `forward_once` but inside a repeating protocol.
+impl<F, P> Split<P> for Rec<F>
+where
+    F: SessionFn + 'static,
+    F::Body<Var<Rec<F>>>: Split<P>,
+{
+    type Remainder = <F::Body<Var<Rec<F>>> as Split<P>>::Remainder;
+
+    unsafe fn split(self) -> (P, Self::Remainder) {
+        // The unfolded body carries its own guards for the borrowed prefix.
+        unsafe { self.enter().split() }
+    }
+}
+
+
+// The loop point itself is left behind as the remainder, so the borrowed prefix ends exactly one
+// unfolding in and the rest of the loop is handed back untouched.
+impl<R> Split<Return<'static>> for Var<R> {
     type Remainder = Self;
 
     unsafe fn split(self) -> (Return<'static>, Self::Remainder) {
@@ -136,26 +554,62 @@ impl<'a> Restricted<'a> for Return<'a>
 {
     type Unrestricted = Return<'static>;
 
-    fn from_unrestricted<T>(unrestricted: Self::Unrestricted, _: &'a T) -> Self {
+    fn from_unrestricted<T>(_unrestricted: Self::Unrestricted, _: &'a T) -> Self {
         Return(PhantomData)
     }
 }
 
 
-impl<'a, U, Cont> Restricted<'a> for Snd<U, Cont> where Cont: Restricted<'a> {
-    type Unrestricted = Snd<U, Cont::Unrestricted>;
+impl<'a, U, Cont, Tr> Restricted<'a> for Snd<U, Cont, Tr> where Cont: Restricted<'a> {
+    type Unrestricted = Snd<U, Cont::Unrestricted, Tr>;
+
+    fn from_unrestricted<T>(unrestricted: Self::Unrestricted, t: &'a T) -> Self {
+        Self(unrestricted.0, Cont::from_unrestricted(unrestricted.1, t), unrestricted.2, PhantomData)
+    }
+}
+
+
+impl<'a, U, Cont, Tr> Restricted<'a> for Recv<U, Cont, Tr> where Cont: Restricted<'a> {
+    type Unrestricted = Recv<U, Cont::Unrestricted, Tr>;
+
+    fn from_unrestricted<T>(unrestricted: Self::Unrestricted, t: &'a T) -> Self {
+        Self(unrestricted.0, Cont::from_unrestricted(unrestricted.1, t), unrestricted.2, PhantomData)
+    }
+}
+
+
+impl<'a, L, R> Restricted<'a> for Choose<L, R>
+where
+    L: Restricted<'a>,
+    R: Restricted<'a>,
+{
+    type Unrestricted = Choose<L::Unrestricted, R::Unrestricted>;
 
     fn from_unrestricted<T>(unrestricted: Self::Unrestricted, t: &'a T) -> Self {
-        Self(unrestricted.0, Cont::from_unrestricted(unrestricted.1, t), unrestricted.2)
+        Self(
+            unrestricted.0,
+            L::from_unrestricted(unrestricted.1, t),
+            R::from_unrestricted(unrestricted.2, t),
+            unrestricted.3,
+        )
     }
 }
 
 
-impl<'a, U, Cont> Restricted<'a> for Recv<U, Cont> where Cont: Restricted<'a> {
-    type Unrestricted = Recv<U, Cont::Unrestricted>;
+impl<'a, L, R> Restricted<'a> for Offer<L, R>
+where
+    L: Restricted<'a>,
+    R: Restricted<'a>,
+{
+    type Unrestricted = Offer<L::Unrestricted, R::Unrestricted>;
 
     fn from_unrestricted<T>(unrestricted: Self::Unrestricted, t: &'a T) -> Self {
-        Self(unrestricted.0, Cont::from_unrestricted(unrestricted.1, t), unrestricted.2)
+        Self(
+            unrestricted.0,
+            L::from_unrestricted(unrestricted.1, t),
+            R::from_unrestricted(unrestricted.2, t),
+            unrestricted.3,
+        )
     }
 }
 
@@ -164,7 +618,8 @@ impl<'a, U, Cont> Restricted<'a> for Recv<U, Cont> where Cont: Restricted<'a> {
 #[macro_export]
 macro_rules! split {
     ($original: ident => $a: ident, $b: ident) => {
-        // Split session - Lifetimes are not yet applied
+        // Split session - Lifetimes are not yet applied. The transport type (and hence any
+        // `ThreadBound` binding) is carried through onto the borrowed prefix by `Split`.
         let (unrestricted, $b) = unsafe {$crate::Split::split($original)};
         let $a = $crate::Restricted::from_unrestricted(unrestricted, &$b);
     }
@@ -185,18 +640,170 @@ macro_rules! recv {
 }
 
 
+// Receive a choice tag and run the matching arm, reconstructing the correct continuation type for
+// the taken branch.
+#[macro_export]
+macro_rules! offer {
+    ($session: ident => $left_sess: ident => $left: block, $right_sess: ident => $right: block) => {
+        match $crate::Offer::offer($session) {
+            $crate::Branch::Left($left_sess) => $left,
+            $crate::Branch::Right($right_sess) => $right,
+        }
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn drop_test() {
-        let p = PanicOnDrop;
+        let p = PanicOnDrop::new();
         p.disarm();
     }
     #[test]
     #[should_panic]
     fn drop_test_2() {
-        let _p = PanicOnDrop;
+        let _p = PanicOnDrop::new();
+    }
+
+    #[test]
+    fn serde_transport_frames_round_trip() {
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let transport = SerdeTransport::new(stream);
+            let got: (u32, String) = transport.recv().unwrap();
+            assert_eq!(got, (42, "hello".to_string()));
+            transport.send(got.0 + 1).unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let transport = SerdeTransport::new(stream);
+        transport.send((42u32, "hello".to_string())).unwrap();
+        let echoed: u32 = transport.recv().unwrap();
+        assert_eq!(echoed, 43);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn session_pair_round_trip() {
+        use std::thread;
+
+        // The constructor hands back two endpoints that are dual by construction.
+        type Proto = Snd<u32, Recv<u32, End>>;
+        let (client, server) = session_pair::<Proto>();
+
+        let worker = thread::spawn(move || {
+            let session = client.send(7);
+            let (doubled, End) = session.recv();
+            assert_eq!(doubled, 14);
+        });
+
+        let (value, session) = server.recv();
+        let End = session.send(value * 2);
+
+        worker.join().unwrap();
+    }
+
+    struct Consumer;
+    impl SessionFn for Consumer {
+        // Each iteration the peer offers another record (left) or signals end (right).
+        type Body<V> = Offer<Recv<u32, V>, End>;
+    }
+
+    struct Producer;
+    impl SessionFn for Producer {
+        type Body<V> = Choose<Snd<u32, V>, End>;
+    }
+
+    #[test]
+    fn rec_loop_streams_records() {
+        use std::thread;
+
+        // The point under test is that each loop is built from a finite step even though the
+        // protocol it describes is unbounded - a `Rec` stores only its step, and the `Var` handed to
+        // each step rebuilds the loop on demand, so there is no infinite value to construct. Running the producer on its
+        // own thread also checks that a recursive session stays `Send`. First value is non-zero so
+        // a dropped first unfolding would change the sum.
+        let (tag_tx, tag_rx) = kanal::unbounded::<bool>();
+        let (data_tx, data_rx) = kanal::unbounded::<u32>();
+
+        let consumer: Rec<Consumer> = Rec::new(move |var| {
+            Offer::new(tag_rx.clone(), Recv::new(data_rx.clone(), var), End)
+        });
+        let producer: Rec<Producer> = Rec::new(move |var| {
+            Choose::new(tag_tx.clone(), Snd::new(data_tx.clone(), var), End)
+        });
+
+        let sender = thread::spawn(move || {
+            let mut loop_ = producer;
+            for i in 1..=3u32 {
+                let step = loop_.enter().choose_left();
+                loop_ = step.send(i).recurse();
+            }
+            let End = loop_.enter().choose_right();
+        });
+
+        let mut loop_ = consumer;
+        let mut total = 0u32;
+        while let Branch::Left(record) = loop_.enter().offer() {
+            let (value, var) = record.recv();
+            total += value;
+            loop_ = var.recurse();
+        }
+        assert_eq!(total, 1 + 2 + 3);
+
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn choose_offer_round_trip() {
+        use std::thread;
+
+        // The active side commits (left) or aborts (right); the peer follows the chosen branch.
+        type Client = Choose<Snd<u32, End>, Snd<String, End>>;
+        let (client, server) = session_pair::<Client>();
+
+        let committer = thread::spawn(move || {
+            let session = client.choose_left();
+            let End = session.send(7);
+        });
+
+        offer!(server =>
+            committed => {
+                let (value, rest) = committed.recv();
+                let End = rest;
+                assert_eq!(value, 7);
+            },
+            aborted => {
+                let (_reason, End) = aborted.recv();
+                panic!("peer committed, not aborted");
+            }
+        );
+
+        committer.join().unwrap();
+    }
+
+    #[test]
+    fn thread_bound_rejects_off_thread_use() {
+        use std::thread;
+
+        let (tx, _rx) = kanal::bounded::<u32>(0);
+        // Bound to this thread; the guard must fire before `send` ever touches the channel.
+        let session: LocalSnd<u32, End> = Snd::new(ThreadBound::new(tx), End);
+
+        let moved = thread::spawn(move || session.send(1)).join();
+        assert!(
+            moved.is_err(),
+            "a thread-bound session used off its creating thread must panic"
+        );
     }
 }